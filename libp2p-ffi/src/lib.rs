@@ -1,19 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ffi::{CStr, CString};
+use std::io;
 use std::os::raw::c_char;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use anyhow::{anyhow, Result};
+use argon2::Argon2;
+use async_trait::async_trait;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, StreamExt};
 use libp2p::{
-    gossipsub, identify, kad, mdns, noise, ping, tcp, yamux,
-    autonat, dcutr,
-    Multiaddr, PeerId, Swarm,
-    swarm::NetworkBehaviour,
+    autonat, bandwidth, dcutr, gossipsub, identify, identity::Keypair, kad, mdns,
+    multiaddr::Protocol as MultiaddrProtocol, noise, ping, relay, request_response,
+    swarm::NetworkBehaviour, swarm::SwarmEvent, tcp, yamux, Multiaddr, PeerId, StreamProtocol,
+    Swarm,
 };
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
 use tracing::{error, info};
 
+const HISTORY_SYNC_PROTOCOL: &str = "/bar123/history-sync/1.0.0";
+
 // FFI-safe types
 #[repr(C)]
 pub struct P2PNode {
@@ -49,13 +62,163 @@ pub struct SyncMessage {
     pub timestamp: i64,
 }
 
+// Request/response types for history backfill, exchanged over the
+// `/bar123/history-sync/1.0.0` protocol.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryBackfillRequest {
+    pub since_timestamp: i64,
+    pub device_id: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryBackfillResponse(pub Vec<HistoryEntry>);
+
+// JSON-over-stream codec for the history backfill protocol, in the spirit
+// of the libp2p file-sharing example: one request, one response, stream
+// closed by the writer when done.
+#[derive(Debug, Clone, Default)]
+struct HistorySyncCodec;
+
+#[async_trait]
+impl request_response::Codec for HistorySyncCodec {
+    type Protocol = StreamProtocol;
+    type Request = HistoryBackfillRequest;
+    type Response = HistoryBackfillResponse;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Self::Request>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::new();
+        io.read_to_end(&mut buf).await?;
+        serde_json::from_slice(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn read_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+    ) -> io::Result<Self::Response>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        let mut buf = Vec::new();
+        io.read_to_end(&mut buf).await?;
+        serde_json::from_slice(&buf).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        req: Self::Request,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let bytes = serde_json::to_vec(&req)?;
+        io.write_all(&bytes).await?;
+        io.close().await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        res: Self::Response,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        let bytes = serde_json::to_vec(&res)?;
+        io.write_all(&bytes).await?;
+        io.close().await
+    }
+}
+
+// Commands sent from the FFI-facing methods into the swarm event loop,
+// since the swarm itself is moved into a spawned task once the node starts.
+enum Command {
+    JoinRoom(String, Option<[u8; 32]>),
+    Publish(Vec<u8>),
+    Listen(u16),
+    RequestHistoryBackfill(i64),
+    AddRelay(Multiaddr),
+}
+
+// Default network load: the midpoint of the 1-5 scale, chosen to match the
+// heartbeat interval this crate used before the profile was configurable.
+const DEFAULT_NETWORK_LOAD: u8 = 3;
+
+// Gossipsub tuning derived from a 1 (battery/data-saving) to 5 (fastest
+// propagation) "network load" dial. Low values trade propagation latency
+// for less egress; high values approach real-time propagation.
+struct GossipsubLoadProfile {
+    heartbeat_interval: Duration,
+    mesh_n: usize,
+    mesh_n_low: usize,
+    mesh_n_high: usize,
+    gossip_factor: f64,
+    history_gossip: usize,
+}
+
+fn gossipsub_load_profile(load: u8) -> GossipsubLoadProfile {
+    match load.clamp(1, 5) {
+        1 => GossipsubLoadProfile {
+            heartbeat_interval: Duration::from_secs(30),
+            mesh_n: 4,
+            mesh_n_low: 2,
+            mesh_n_high: 6,
+            gossip_factor: 0.1,
+            history_gossip: 2,
+        },
+        2 => GossipsubLoadProfile {
+            heartbeat_interval: Duration::from_secs(20),
+            mesh_n: 5,
+            mesh_n_low: 3,
+            mesh_n_high: 8,
+            gossip_factor: 0.15,
+            history_gossip: 3,
+        },
+        3 => GossipsubLoadProfile {
+            heartbeat_interval: Duration::from_secs(10),
+            mesh_n: 6,
+            mesh_n_low: 4,
+            mesh_n_high: 12,
+            gossip_factor: 0.25,
+            history_gossip: 5,
+        },
+        4 => GossipsubLoadProfile {
+            heartbeat_interval: Duration::from_secs(5),
+            mesh_n: 8,
+            mesh_n_low: 6,
+            mesh_n_high: 14,
+            gossip_factor: 0.35,
+            history_gossip: 7,
+        },
+        _ => GossipsubLoadProfile {
+            heartbeat_interval: Duration::from_secs(1),
+            mesh_n: 10,
+            mesh_n_low: 8,
+            mesh_n_high: 16,
+            gossip_factor: 0.5,
+            history_gossip: 10,
+        },
+    }
+}
+
 // Internal node structure
 struct NodeInner {
     runtime: Runtime,
     swarm: Option<Swarm<MyBehaviour>>,
+    keypair: Keypair,
     peer_id: PeerId,
     room_topic: Option<String>,
-    connected_peers: HashMap<PeerId, bool>,
+    connected_peers: Arc<Mutex<HashMap<PeerId, bool>>>,
+    history_cache: Arc<Mutex<Vec<HistoryEntry>>>,
+    authorized_peers: Arc<Mutex<HashSet<PeerId>>>,
+    command_tx: Option<mpsc::UnboundedSender<Command>>,
+    bandwidth_sinks: Option<Arc<bandwidth::BandwidthSinks>>,
 }
 
 // Network behaviour
@@ -68,6 +231,8 @@ struct MyBehaviour {
     ping: ping::Behaviour,
     autonat: autonat::Behaviour,
     dcutr: dcutr::Behaviour,
+    history_sync: request_response::Behaviour<HistorySyncCodec>,
+    relay_client: relay::client::Behaviour,
 }
 
 // Callback function type for Swift
@@ -79,27 +244,55 @@ static mut PEER_CALLBACK: Option<PeerCallback> = None;
 
 impl NodeInner {
     fn new() -> Result<Self> {
+        Self::new_with_keypair(Keypair::generate_ed25519())
+    }
+
+    // Builds a node around a caller-supplied keypair so the peer id stays
+    // stable across launches instead of being regenerated every time.
+    fn new_with_keypair(keypair: Keypair) -> Result<Self> {
         let runtime = Runtime::new()?;
-        let keypair = libp2p::identity::Keypair::generate_ed25519();
         let peer_id = PeerId::from(keypair.public());
-        
+
         Ok(Self {
             runtime,
             swarm: None,
+            keypair,
             peer_id,
             room_topic: None,
-            connected_peers: HashMap::new(),
+            connected_peers: Arc::new(Mutex::new(HashMap::new())),
+            history_cache: Arc::new(Mutex::new(Vec::new())),
+            authorized_peers: Arc::new(Mutex::new(HashSet::new())),
+            command_tx: None,
+            bandwidth_sinks: None,
         })
     }
 
     fn initialize_swarm(&mut self) -> Result<()> {
-        let keypair = libp2p::identity::Keypair::generate_ed25519();
-        let peer_id = PeerId::from(keypair.public());
-        
+        self.initialize_swarm_with_load(DEFAULT_NETWORK_LOAD)
+    }
+
+    // Same as `initialize_swarm`, but `load` (1-5) tunes gossipsub toward
+    // less egress (1) or faster propagation (5) for battery/data-constrained
+    // mobile clients.
+    fn initialize_swarm_with_load(&mut self, load: u8) -> Result<()> {
+        let keypair = self.keypair.clone();
+        let peer_id = self.peer_id;
+        let profile = gossipsub_load_profile(load);
+
         // Create gossipsub behaviour
         let gossipsub_config = gossipsub::ConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(10))
+            .heartbeat_interval(profile.heartbeat_interval)
+            .mesh_n(profile.mesh_n)
+            .mesh_n_low(profile.mesh_n_low)
+            .mesh_n_high(profile.mesh_n_high)
+            .gossip_factor(profile.gossip_factor)
+            .history_gossip(profile.history_gossip)
             .validation_mode(gossipsub::ValidationMode::Strict)
+            // Hold every message for an explicit accept/reject via
+            // `report_message_validation_result` instead of forwarding it
+            // to the mesh immediately, so the allow-list check in the event
+            // loop can stop an unauthorized message from propagating.
+            .validate_messages()
             .message_id_fn(|message: &gossipsub::Message| {
                 use std::collections::hash_map::DefaultHasher;
                 use std::hash::{Hash, Hasher};
@@ -113,7 +306,8 @@ impl NodeInner {
         let mut gossipsub = gossipsub::Behaviour::new(
             gossipsub::MessageAuthenticity::Signed(keypair.clone()),
             gossipsub_config,
-        ).map_err(|e| anyhow!("Failed to create gossipsub behaviour: {}", e))?;
+        )
+        .map_err(|e| anyhow!("Failed to create gossipsub behaviour: {}", e))?;
 
         // Subscribe to history sync topic
         let topic = gossipsub::IdentTopic::new("bar123-history-sync");
@@ -125,92 +319,611 @@ impl NodeInner {
             "/bar123/1.0.0".to_string(),
             keypair.public(),
         ));
-        
+
         let kad_store = kad::store::MemoryStore::new(peer_id);
         let mut kad = kad::Behaviour::new(peer_id, kad_store);
         kad.set_mode(Some(kad::Mode::Server));
-        
+
         let ping = ping::Behaviour::new(ping::Config::new());
-        
+
         // NAT traversal
         let autonat = autonat::Behaviour::new(peer_id, autonat::Config::default());
         let dcutr = dcutr::Behaviour::new(peer_id);
 
-        // Combine behaviours
-        let behaviour = MyBehaviour {
-            gossipsub,
-            mdns,
-            identify,
-            kad,
-            ping,
-            autonat,
-            dcutr,
-        };
+        let history_sync = request_response::Behaviour::new(
+            [(
+                StreamProtocol::new(HISTORY_SYNC_PROTOCOL),
+                request_response::ProtocolSupport::Full,
+            )],
+            request_response::Config::default(),
+        );
+
+        // Create swarm. The relay client transport set up by
+        // `with_relay_client` is what lets `dcutr` actually attempt hole
+        // punching once a relayed connection is established. Bandwidth
+        // logging wraps the transport so `p2p_get_bandwidth` can report
+        // live usage to the Swift UI.
+        let (swarm_builder, bandwidth_sinks) =
+            libp2p::SwarmBuilder::with_existing_identity(keypair)
+                .with_tokio()
+                .with_tcp(
+                    tcp::Config::default(),
+                    noise::Config::new,
+                    yamux::Config::default,
+                )?
+                .with_quic()
+                .with_relay_client(noise::Config::new, yamux::Config::default)?
+                .with_bandwidth_logging();
 
-        // Create swarm
-        let swarm = libp2p::SwarmBuilder::with_existing_identity(keypair)
-            .with_tokio()
-            .with_tcp(
-                tcp::Config::default(),
-                noise::Config::new,
-                yamux::Config::default,
-            )?
-            .with_quic()
-            .with_behaviour(|_| behaviour)?
+        let swarm = swarm_builder
+            .with_behaviour(|_, relay_client| MyBehaviour {
+                gossipsub,
+                mdns,
+                identify,
+                kad,
+                ping,
+                autonat,
+                dcutr,
+                history_sync,
+                relay_client,
+            })?
             .with_swarm_config(|c| c.with_idle_connection_timeout(Duration::from_secs(60)))
             .build();
 
         self.swarm = Some(swarm);
-        self.peer_id = peer_id;
-        
+        self.bandwidth_sinks = Some(bandwidth_sinks);
+
         Ok(())
     }
 
-    fn start_listening(&mut self, port: u16) -> Result<()> {
-        if let Some(swarm) = &mut self.swarm {
-            // Listen on TCP
-            let tcp_addr = format!("/ip4/0.0.0.0/tcp/{}", port).parse::<Multiaddr>()?;
-            swarm.listen_on(tcp_addr)?;
-            
-            // Also listen on a random UDP port for QUIC (better NAT traversal)
-            let quic_addr = "/ip4/0.0.0.0/udp/0/quic-v1".parse::<Multiaddr>()?;
-            swarm.listen_on(quic_addr)?;
-            
-            info!("Listening on TCP port {} and QUIC", port);
-            
-            // Bootstrap Kademlia
-            swarm.behaviour_mut().kad.bootstrap()?;
-            
-            Ok(())
-        } else {
-            Err(anyhow!("Swarm not initialized"))
+    // Moves the swarm into a spawned task running the event loop and wires
+    // up the command channel used by the other methods below. Idempotent:
+    // once the loop is running, later calls just reuse the existing sender.
+    fn ensure_event_loop(&mut self) -> Result<mpsc::UnboundedSender<Command>> {
+        if let Some(tx) = &self.command_tx {
+            return Ok(tx.clone());
         }
+
+        let swarm = self
+            .swarm
+            .take()
+            .ok_or_else(|| anyhow!("Swarm not initialized"))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        let connected_peers = self.connected_peers.clone();
+        let history_cache = self.history_cache.clone();
+        let authorized_peers = self.authorized_peers.clone();
+        self.runtime.spawn(run_event_loop(
+            swarm,
+            rx,
+            connected_peers,
+            history_cache,
+            authorized_peers,
+        ));
+        self.command_tx = Some(tx.clone());
+        Ok(tx)
+    }
+
+    fn start_listening(&mut self, port: u16) -> Result<()> {
+        let tx = self.ensure_event_loop()?;
+        tx.send(Command::Listen(port))
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+        info!("Requested listen on TCP port {} and QUIC", port);
+        Ok(())
     }
 
     fn join_room(&mut self, room_id: &str) -> Result<()> {
+        self.join_room_with_key(room_id, None)
+    }
+
+    // Joins the room with its payloads encrypted under a key derived from
+    // `passphrase`, so a bare subscriber to the gossipsub topic can't read
+    // the plaintext history being exchanged.
+    fn join_room_encrypted(&mut self, room_id: &str, passphrase: &str) -> Result<()> {
+        let key = derive_room_key(room_id, passphrase)?;
+        self.join_room_with_key(room_id, Some(key))
+    }
+
+    fn join_room_with_key(&mut self, room_id: &str, key: Option<[u8; 32]>) -> Result<()> {
         self.room_topic = Some(format!("bar123-room-{}", room_id));
-        
-        if let Some(swarm) = &mut self.swarm {
-            let topic = gossipsub::IdentTopic::new(self.room_topic.as_ref().unwrap().clone());
-            swarm.behaviour_mut().gossipsub.subscribe(&topic)?;
-            info!("Joined room: {}", room_id);
-            Ok(())
-        } else {
-            Err(anyhow!("Swarm not initialized"))
-        }
+
+        let tx = self.ensure_event_loop()?;
+        tx.send(Command::JoinRoom(room_id.to_string(), key))
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+        info!("Joined room: {}", room_id);
+        Ok(())
+    }
+
+    fn authorize_peer(&mut self, peer_id: PeerId) {
+        self.authorized_peers.lock().unwrap().insert(peer_id);
     }
 
     fn send_message(&mut self, data: &[u8]) -> Result<()> {
-        if let (Some(swarm), Some(topic)) = (&mut self.swarm, &self.room_topic) {
-            let topic = gossipsub::IdentTopic::new(topic);
-            swarm.behaviour_mut().gossipsub.publish(topic, data)?;
-            Ok(())
-        } else {
-            Err(anyhow!("Not connected to a room"))
+        if self.room_topic.is_none() {
+            return Err(anyhow!("Not connected to a room"));
+        }
+
+        let tx = self
+            .command_tx
+            .as_ref()
+            .ok_or_else(|| anyhow!("Event loop is not running"))?;
+        tx.send(Command::Publish(data.to_vec()))
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+        Ok(())
+    }
+
+    fn request_history_backfill(&mut self, since_timestamp: i64) -> Result<()> {
+        if self.room_topic.is_none() {
+            return Err(anyhow!("Not connected to a room"));
+        }
+
+        let tx = self
+            .command_tx
+            .as_ref()
+            .ok_or_else(|| anyhow!("Event loop is not running"))?;
+        tx.send(Command::RequestHistoryBackfill(since_timestamp))
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+        Ok(())
+    }
+
+    fn add_relay(&mut self, relay_addr: Multiaddr) -> Result<()> {
+        let tx = self.ensure_event_loop()?;
+        tx.send(Command::AddRelay(relay_addr))
+            .map_err(|_| anyhow!("Event loop is not running"))?;
+        Ok(())
+    }
+
+    fn bandwidth_totals(&self) -> Result<(u64, u64)> {
+        let sinks = self
+            .bandwidth_sinks
+            .as_ref()
+            .ok_or_else(|| anyhow!("Swarm not initialized"))?;
+        Ok((sinks.total_inbound(), sinks.total_outbound()))
+    }
+}
+
+// Kademlia key that peers in a room advertise themselves under, so late
+// joiners can locate providers to pull a history backfill from.
+fn history_provider_key(room_topic: &str) -> kad::RecordKey {
+    kad::RecordKey::new(&format!("{}-history-provider", room_topic))
+}
+
+// Adds `entries` to the shared history cache, skipping any that are already
+// present (same device, URL and visit time) so entries authored locally and
+// entries received live over gossipsub can merge without duplicating a
+// backfill response.
+fn merge_history_entries(cache: &Arc<Mutex<Vec<HistoryEntry>>>, entries: Vec<HistoryEntry>) {
+    let mut cache = cache.lock().unwrap();
+    for entry in entries {
+        let already_cached = cache.iter().any(|e| {
+            e.device_id == entry.device_id && e.url == entry.url && e.visit_time == entry.visit_time
+        });
+        if !already_cached {
+            cache.push(entry);
+        }
+    }
+}
+
+// Derives a per-room symmetric key from a user-supplied passphrase. Argon2
+// (rather than a plain HKDF) is used because a passphrase is low-entropy
+// and needs to be stretched; the room id doubles as the salt so two rooms
+// sharing a passphrase still end up with unrelated keys.
+fn derive_room_key(room_id: &str, passphrase: &str) -> Result<[u8; 32]> {
+    let mut salt = Sha256::new();
+    salt.update(b"bar123-room-salt-v1");
+    salt.update(room_id.as_bytes());
+    let salt = salt.finalize();
+
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+        .map_err(|e| anyhow!("Failed to derive room key: {}", e))?;
+    Ok(key)
+}
+
+// Encrypts `plaintext` with ChaCha20-Poly1305 under `key`, prefixing the
+// ciphertext with a fresh random nonce so the receiver can decrypt it.
+fn encrypt_room_payload(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let mut nonce_bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce_bytes);
+    let nonce = Nonce::from_slice(&nonce_bytes);
+
+    let ciphertext = cipher
+        .encrypt(nonce, plaintext)
+        .map_err(|e| anyhow!("Failed to encrypt room payload: {}", e))?;
+
+    let mut out = Vec::with_capacity(nonce_bytes.len() + ciphertext.len());
+    out.extend_from_slice(&nonce_bytes);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+// Inverse of `encrypt_room_payload`. Returns an error (rather than panicking)
+// for truncated or forged ciphertext, so callers can drop it instead of
+// crashing the event loop.
+fn decrypt_room_payload(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 12 {
+        return Err(anyhow!("Ciphertext shorter than nonce"));
+    }
+    let (nonce_bytes, ciphertext) = data.split_at(12);
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce_bytes), ciphertext)
+        .map_err(|e| anyhow!("Failed to decrypt room payload: {}", e))
+}
+
+// Listens on `relay_addr` extended with `/p2p-circuit`, which asks the relay
+// for a reservation so inbound peers can reach us through it and, from
+// there, attempt a `dcutr` hole punch.
+fn request_relay_reservation(swarm: &mut Swarm<MyBehaviour>, relay_addr: &Multiaddr) {
+    let circuit_addr = relay_addr.clone().with(MultiaddrProtocol::P2pCircuit);
+    if let Err(e) = swarm.listen_on(circuit_addr) {
+        error!(
+            "Failed to request relay reservation on {}: {}",
+            relay_addr, e
+        );
+    }
+}
+
+// Drains swarm events and commands until the command channel is dropped.
+// Runs on `NodeInner.runtime` so the FFI surface stays non-blocking.
+async fn run_event_loop(
+    mut swarm: Swarm<MyBehaviour>,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+    connected_peers: Arc<Mutex<HashMap<PeerId, bool>>>,
+    history_cache: Arc<Mutex<Vec<HistoryEntry>>>,
+    authorized_peers: Arc<Mutex<HashSet<PeerId>>>,
+) {
+    let local_peer_id = *swarm.local_peer_id();
+    let mut room_topic: Option<String> = None;
+    // Set when the room was joined via `p2p_join_room_encrypted`; encrypts
+    // outgoing publishes and decrypts incoming messages for this room.
+    let mut room_key: Option<[u8; 32]> = None;
+    // Maps an in-flight `get_providers` query back to the backfill request
+    // that triggered it, so we know which `since_timestamp` to ask for.
+    let mut pending_backfills: HashMap<kad::QueryId, i64> = HashMap::new();
+    // Relays we've dialed, so we can request a reservation on all of them
+    // as soon as AutoNAT tells us we're behind a NAT.
+    let mut known_relays: Vec<Multiaddr> = Vec::new();
+
+    loop {
+        tokio::select! {
+            event = swarm.select_next_some() => {
+                handle_swarm_event(
+                    &mut swarm,
+                    event,
+                    &connected_peers,
+                    &history_cache,
+                    &authorized_peers,
+                    &room_key,
+                    &mut pending_backfills,
+                    &known_relays,
+                    local_peer_id,
+                );
+            }
+            cmd = commands.recv() => {
+                match cmd {
+                    Some(Command::Listen(port)) => {
+                        let tcp_addr = match format!("/ip4/0.0.0.0/tcp/{}", port).parse::<Multiaddr>() {
+                            Ok(addr) => addr,
+                            Err(e) => {
+                                error!("Invalid TCP listen address: {}", e);
+                                continue;
+                            }
+                        };
+                        if let Err(e) = swarm.listen_on(tcp_addr) {
+                            error!("Failed to listen on TCP port {}: {}", port, e);
+                        }
+
+                        let quic_addr = "/ip4/0.0.0.0/udp/0/quic-v1"
+                            .parse::<Multiaddr>()
+                            .expect("static QUIC multiaddr is valid");
+                        if let Err(e) = swarm.listen_on(quic_addr) {
+                            error!("Failed to listen on QUIC: {}", e);
+                        }
+
+                        if let Err(e) = swarm.behaviour_mut().kad.bootstrap() {
+                            error!("Failed to bootstrap Kademlia: {}", e);
+                        }
+                    }
+                    Some(Command::JoinRoom(room_id, key)) => {
+                        let topic_name = format!("bar123-room-{}", room_id);
+                        let topic = gossipsub::IdentTopic::new(topic_name.clone());
+                        if let Err(e) = swarm.behaviour_mut().gossipsub.subscribe(&topic) {
+                            error!("Failed to subscribe to room {}: {}", room_id, e);
+                            continue;
+                        }
+                        if let Err(e) = swarm
+                            .behaviour_mut()
+                            .kad
+                            .start_providing(history_provider_key(&topic_name))
+                        {
+                            error!("Failed to advertise as a history provider: {}", e);
+                        }
+                        room_topic = Some(topic_name);
+                        room_key = key;
+                    }
+                    Some(Command::Publish(data)) => {
+                        let Some(topic_name) = &room_topic else {
+                            error!("Dropping publish: not subscribed to a room yet");
+                            continue;
+                        };
+                        let payload = match &room_key {
+                            Some(key) => match encrypt_room_payload(key, &data) {
+                                Ok(ciphertext) => ciphertext,
+                                Err(e) => {
+                                    error!("Dropping publish: failed to encrypt: {}", e);
+                                    continue;
+                                }
+                            },
+                            None => data,
+                        };
+                        let topic = gossipsub::IdentTopic::new(topic_name.clone());
+                        if let Err(e) = swarm.behaviour_mut().gossipsub.publish(topic, payload) {
+                            error!("Failed to publish message: {}", e);
+                        }
+                    }
+                    Some(Command::RequestHistoryBackfill(since_timestamp)) => {
+                        let Some(topic_name) = &room_topic else {
+                            error!("Dropping backfill request: not subscribed to a room yet");
+                            continue;
+                        };
+                        let query_id = swarm
+                            .behaviour_mut()
+                            .kad
+                            .get_providers(history_provider_key(topic_name));
+                        pending_backfills.insert(query_id, since_timestamp);
+                    }
+                    Some(Command::AddRelay(relay_addr)) => {
+                        if let Err(e) = swarm.dial(relay_addr.clone()) {
+                            error!("Failed to dial relay {}: {}", relay_addr, e);
+                            continue;
+                        }
+                        request_relay_reservation(&mut swarm, &relay_addr);
+                        known_relays.push(relay_addr);
+                    }
+                    None => break,
+                }
+            }
+        }
+    }
+}
+
+fn handle_swarm_event(
+    swarm: &mut Swarm<MyBehaviour>,
+    event: SwarmEvent<MyBehaviourEvent>,
+    connected_peers: &Arc<Mutex<HashMap<PeerId, bool>>>,
+    history_cache: &Arc<Mutex<Vec<HistoryEntry>>>,
+    authorized_peers: &Arc<Mutex<HashSet<PeerId>>>,
+    room_key: &Option<[u8; 32]>,
+    pending_backfills: &mut HashMap<kad::QueryId, i64>,
+    known_relays: &[Multiaddr],
+    local_peer_id: PeerId,
+) {
+    match event {
+        SwarmEvent::Behaviour(MyBehaviourEvent::Gossipsub(gossipsub::Event::Message {
+            propagation_source,
+            message_id,
+            message,
+        })) => {
+            // `propagation_source` is whoever relayed this to us, not its
+            // author — with `MessageAuthenticity::Signed` the authenticated
+            // author is `message.source`. The allow-list must check that,
+            // or a paired relay can forward an unpaired device's message.
+            let authorized = authorized_peers.lock().unwrap();
+            let author_is_authorized = authorized.is_empty()
+                || message
+                    .source
+                    .is_some_and(|author| authorized.contains(&author));
+            drop(authorized);
+
+            if !author_is_authorized {
+                info!(
+                    "Rejecting message from unauthorized author {:?}",
+                    message.source
+                );
+                let _ = swarm
+                    .behaviour_mut()
+                    .gossipsub
+                    .report_message_validation_result(
+                        &message_id,
+                        &propagation_source,
+                        gossipsub::MessageAcceptance::Reject,
+                    );
+                return;
+            }
+
+            let plaintext = match room_key {
+                Some(key) => match decrypt_room_payload(key, &message.data) {
+                    Ok(plaintext) => plaintext,
+                    Err(e) => {
+                        info!("Rejecting message that failed authentication: {}", e);
+                        let _ = swarm
+                            .behaviour_mut()
+                            .gossipsub
+                            .report_message_validation_result(
+                                &message_id,
+                                &propagation_source,
+                                gossipsub::MessageAcceptance::Reject,
+                            );
+                        return;
+                    }
+                },
+                None => message.data.clone(),
+            };
+
+            let _ = swarm
+                .behaviour_mut()
+                .gossipsub
+                .report_message_validation_result(
+                    &message_id,
+                    &propagation_source,
+                    gossipsub::MessageAcceptance::Accept,
+                );
+
+            // Merge live history-sync entries into the local cache (deduped)
+            // so this device can serve them to a late joiner over the history
+            // backfill protocol too, not just entries it authored itself.
+            if let Ok(sync_message) = serde_json::from_slice::<SyncMessage>(&plaintext) {
+                if sync_message.message_type == "history_sync" {
+                    merge_history_entries(history_cache, sync_message.entries);
+                }
+            }
+
+            invoke_message_callback(
+                &propagation_source,
+                &message.topic.into_string(),
+                &plaintext,
+            );
+        }
+        SwarmEvent::Behaviour(MyBehaviourEvent::HistorySync(
+            request_response::Event::Message {
+                peer,
+                message:
+                    request_response::Message::Request {
+                        request, channel, ..
+                    },
+            },
+        )) => {
+            let entries: Vec<HistoryEntry> = history_cache
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|e| e.visit_time > request.since_timestamp)
+                .cloned()
+                .collect();
+            info!(
+                "Serving {} history entries to {} since {}",
+                entries.len(),
+                peer,
+                request.since_timestamp
+            );
+            if swarm
+                .behaviour_mut()
+                .history_sync
+                .send_response(channel, HistoryBackfillResponse(entries))
+                .is_err()
+            {
+                error!("Failed to send history backfill response to {}", peer);
+            }
+        }
+        SwarmEvent::Behaviour(MyBehaviourEvent::HistorySync(
+            request_response::Event::Message {
+                peer,
+                message: request_response::Message::Response { response, .. },
+            },
+        )) => {
+            let sync_message = SyncMessage {
+                message_type: "history_backfill".to_string(),
+                entries: response.0,
+                device_id: peer.to_string(),
+                timestamp: 0,
+            };
+            if let Ok(data) = serde_json::to_vec(&sync_message) {
+                invoke_message_callback(&peer, "history-backfill", &data);
+            }
+        }
+        SwarmEvent::Behaviour(MyBehaviourEvent::Kad(kad::Event::OutboundQueryProgressed {
+            id,
+            result:
+                kad::QueryResult::GetProviders(Ok(kad::GetProvidersOk::FoundProviders {
+                    providers,
+                    ..
+                })),
+            ..
+        })) => {
+            if let Some(since_timestamp) = pending_backfills.remove(&id) {
+                for provider in providers {
+                    if provider == local_peer_id {
+                        continue;
+                    }
+                    swarm.behaviour_mut().history_sync.send_request(
+                        &provider,
+                        HistoryBackfillRequest {
+                            since_timestamp,
+                            device_id: local_peer_id.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+        SwarmEvent::Behaviour(MyBehaviourEvent::Autonat(autonat::Event::StatusChanged {
+            new: autonat::NatStatus::Private,
+            ..
+        })) => {
+            info!(
+                "AutoNAT reports we're behind a NAT; requesting reservations on {} known relay(s)",
+                known_relays.len()
+            );
+            for relay_addr in known_relays {
+                request_relay_reservation(swarm, relay_addr);
+            }
+        }
+        SwarmEvent::ConnectionEstablished {
+            peer_id,
+            num_established,
+            ..
+        } => {
+            // This node listens on both QUIC and TCP, so a reachable peer
+            // can end up with more than one connection; only the first one
+            // is a presence change.
+            if num_established.get() == 1 {
+                connected_peers.lock().unwrap().insert(peer_id, true);
+                invoke_peer_callback(&peer_id, true);
+            }
         }
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            num_established,
+            ..
+        } => {
+            // Only report the peer as gone once its last connection (across
+            // every transport) has closed.
+            if num_established == 0 {
+                connected_peers.lock().unwrap().insert(peer_id, false);
+                invoke_peer_callback(&peer_id, false);
+            }
+        }
+        SwarmEvent::NewListenAddr { address, .. } => {
+            info!("Listening on {}", address);
+        }
+        _ => {}
     }
 }
 
+fn invoke_message_callback(peer_id: &PeerId, topic: &str, data: &[u8]) {
+    let callback = unsafe { MESSAGE_CALLBACK };
+    let Some(callback) = callback else { return };
+
+    let Ok(peer_id_c) = CString::new(peer_id.to_string()) else {
+        return;
+    };
+    let Ok(topic_c) = CString::new(topic) else {
+        return;
+    };
+    let Ok(data_c) = CString::new(data) else {
+        return;
+    };
+
+    let message = P2PMessage {
+        peer_id: peer_id_c.as_ptr(),
+        topic: topic_c.as_ptr(),
+        data: data_c.as_ptr(),
+        data_len: data.len(),
+    };
+    callback(&message as *const P2PMessage);
+}
+
+fn invoke_peer_callback(peer_id: &PeerId, joined: bool) {
+    let callback = unsafe { PEER_CALLBACK };
+    let Some(callback) = callback else { return };
+
+    let Ok(peer_id_c) = CString::new(peer_id.to_string()) else {
+        return;
+    };
+    callback(peer_id_c.as_ptr(), joined);
+}
+
 // FFI functions
 #[no_mangle]
 pub extern "C" fn p2p_node_create() -> *mut P2PNode {
@@ -228,6 +941,76 @@ pub extern "C" fn p2p_node_create() -> *mut P2PNode {
     }
 }
 
+// Takes `key_bytes` as base64-encoded protobuf, matching what
+// `p2p_node_export_identity` returns, so the Swift layer can round-trip a
+// keychain-stored identity through these two calls without re-encoding it.
+#[no_mangle]
+pub extern "C" fn p2p_node_create_with_identity(
+    key_bytes: *const u8,
+    key_len: usize,
+) -> *mut P2PNode {
+    if key_bytes.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let b64 = unsafe { std::slice::from_raw_parts(key_bytes, key_len) };
+    let encoded = match base64::engine::general_purpose::STANDARD.decode(b64) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            error!("Failed to base64-decode identity keypair: {}", e);
+            return std::ptr::null_mut();
+        }
+    };
+    let keypair = match Keypair::from_protobuf_encoding(&encoded) {
+        Ok(k) => k,
+        Err(e) => {
+            error!("Failed to decode identity keypair: {}", e);
+            return std::ptr::null_mut();
+        }
+    };
+
+    match NodeInner::new_with_keypair(keypair) {
+        Ok(inner) => {
+            let node = P2PNode {
+                inner: Box::into_raw(Box::new(inner)),
+            };
+            Box::into_raw(Box::new(node))
+        }
+        Err(e) => {
+            error!("Failed to create P2P node with identity: {}", e);
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// Returns the node's private key as a base64-encoded protobuf string so the
+// caller can persist it (e.g. in the Swift layer's keychain). Pass the
+// string's bytes straight back into `p2p_node_create_with_identity` — do
+// not base64-decode it first, that call does so itself. Free with
+// `p2p_free_string`.
+#[no_mangle]
+pub extern "C" fn p2p_node_export_identity(node: *mut P2PNode) -> *mut c_char {
+    if node.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    unsafe {
+        let inner = &*(*node).inner;
+        let encoded = match inner.keypair.to_protobuf_encoding() {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                error!("Failed to encode identity keypair: {}", e);
+                return std::ptr::null_mut();
+            }
+        };
+        let b64 = base64::engine::general_purpose::STANDARD.encode(encoded);
+        match CString::new(b64) {
+            Ok(s) => s.into_raw(),
+            Err(_) => std::ptr::null_mut(),
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn p2p_node_destroy(node: *mut P2PNode) {
     if !node.is_null() {
@@ -245,7 +1028,7 @@ pub extern "C" fn p2p_node_initialize(node: *mut P2PNode) -> bool {
     if node.is_null() {
         return false;
     }
-    
+
     unsafe {
         let inner = &mut *(*node).inner;
         match inner.initialize_swarm() {
@@ -258,12 +1041,33 @@ pub extern "C" fn p2p_node_initialize(node: *mut P2PNode) -> bool {
     }
 }
 
+// Same as `p2p_node_initialize`, but tunes gossipsub for a 1 (data-saving)
+// to 5 (fastest propagation) network load profile instead of the default
+// midpoint.
+#[no_mangle]
+pub extern "C" fn p2p_node_initialize_with_load(node: *mut P2PNode, load: u8) -> bool {
+    if node.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let inner = &mut *(*node).inner;
+        match inner.initialize_swarm_with_load(load) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to initialize swarm: {}", e);
+                false
+            }
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn p2p_node_start_listening(node: *mut P2PNode, port: u16) -> bool {
     if node.is_null() {
         return false;
     }
-    
+
     unsafe {
         let inner = &mut *(*node).inner;
         match inner.start_listening(port) {
@@ -281,13 +1085,13 @@ pub extern "C" fn p2p_node_join_room(node: *mut P2PNode, room_id: *const c_char)
     if node.is_null() || room_id.is_null() {
         return false;
     }
-    
+
     unsafe {
         let room_id_str = match CStr::from_ptr(room_id).to_str() {
             Ok(s) => s,
             Err(_) => return false,
         };
-        
+
         let inner = &mut *(*node).inner;
         match inner.join_room(room_id_str) {
             Ok(_) => true,
@@ -299,6 +1103,69 @@ pub extern "C" fn p2p_node_join_room(node: *mut P2PNode, room_id: *const c_char)
     }
 }
 
+// Joins a room with a passphrase-derived room key: outgoing messages are
+// ChaCha20-Poly1305-encrypted and incoming messages that fail authentication
+// are dropped before reaching the message callback.
+#[no_mangle]
+pub extern "C" fn p2p_join_room_encrypted(
+    node: *mut P2PNode,
+    room_id: *const c_char,
+    passphrase: *const c_char,
+) -> bool {
+    if node.is_null() || room_id.is_null() || passphrase.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let room_id_str = match CStr::from_ptr(room_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let passphrase_str = match CStr::from_ptr(passphrase).to_str() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+
+        let inner = &mut *(*node).inner;
+        match inner.join_room_encrypted(room_id_str, passphrase_str) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to join encrypted room: {}", e);
+                false
+            }
+        }
+    }
+}
+
+// Adds `peer_id` to the node's allow-list, which applies to every topic the
+// node is subscribed to; messages from peers not on the
+// list are dropped once pairing has added at least one entry. Pairing
+// itself (the exchange that hands out this peer id) happens out-of-band.
+#[no_mangle]
+pub extern "C" fn p2p_authorize_peer(node: *mut P2PNode, peer_id: *const c_char) -> bool {
+    if node.is_null() || peer_id.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let peer_id_str = match CStr::from_ptr(peer_id).to_str() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let peer_id = match peer_id_str.parse::<PeerId>() {
+            Ok(p) => p,
+            Err(e) => {
+                error!("Invalid peer id: {}", e);
+                return false;
+            }
+        };
+
+        let inner = &mut *(*node).inner;
+        inner.authorize_peer(peer_id);
+        true
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn p2p_node_send_message(
     node: *mut P2PNode,
@@ -308,11 +1175,11 @@ pub extern "C" fn p2p_node_send_message(
     if node.is_null() || data.is_null() {
         return false;
     }
-    
+
     unsafe {
         let data_slice = std::slice::from_raw_parts(data, data_len);
         let inner = &mut *(*node).inner;
-        
+
         match inner.send_message(data_slice) {
             Ok(_) => true,
             Err(e) => {
@@ -347,18 +1214,18 @@ pub extern "C" fn p2p_send_history_sync(
     if node.is_null() || entries_json.is_null() || device_id.is_null() {
         return false;
     }
-    
+
     unsafe {
         let entries_str = match CStr::from_ptr(entries_json).to_str() {
             Ok(s) => s,
             Err(_) => return false,
         };
-        
+
         let device_id_str = match CStr::from_ptr(device_id).to_str() {
             Ok(s) => s,
             Err(_) => return false,
         };
-        
+
         let entries: Vec<HistoryEntry> = match serde_json::from_str(entries_str) {
             Ok(e) => e,
             Err(e) => {
@@ -366,7 +1233,7 @@ pub extern "C" fn p2p_send_history_sync(
                 return false;
             }
         };
-        
+
         let sync_message = SyncMessage {
             message_type: "history_sync".to_string(),
             entries,
@@ -376,7 +1243,7 @@ pub extern "C" fn p2p_send_history_sync(
                 .unwrap()
                 .as_millis() as i64,
         };
-        
+
         let message_json = match serde_json::to_vec(&sync_message) {
             Ok(j) => j,
             Err(e) => {
@@ -384,10 +1251,13 @@ pub extern "C" fn p2p_send_history_sync(
                 return false;
             }
         };
-        
+
         let inner = &mut *(*node).inner;
         match inner.send_message(&message_json) {
-            Ok(_) => true,
+            Ok(_) => {
+                merge_history_entries(&inner.history_cache, sync_message.entries);
+                true
+            }
             Err(e) => {
                 error!("Failed to send history sync: {}", e);
                 false
@@ -396,12 +1266,96 @@ pub extern "C" fn p2p_send_history_sync(
     }
 }
 
+// Pulls history entries broadcast before this device subscribed, by locating
+// peers that advertised themselves as history providers for the current room
+// and requesting everything newer than `since_timestamp`. Results arrive
+// asynchronously through the message callback with topic "history-backfill".
+#[no_mangle]
+pub extern "C" fn p2p_request_history_backfill(node: *mut P2PNode, since_timestamp: i64) -> bool {
+    if node.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let inner = &mut *(*node).inner;
+        match inner.request_history_backfill(since_timestamp) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to request history backfill: {}", e);
+                false
+            }
+        }
+    }
+}
+
+// Dials a relay and requests a `/p2p-circuit` reservation on it so inbound
+// peers can reach this node (and trigger a `dcutr` hole punch) even behind
+// a symmetric NAT. Reservations on every added relay are also refreshed
+// automatically whenever AutoNAT reports the node is private.
+#[no_mangle]
+pub extern "C" fn p2p_add_relay(node: *mut P2PNode, relay_multiaddr: *const c_char) -> bool {
+    if node.is_null() || relay_multiaddr.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let relay_addr_str = match CStr::from_ptr(relay_multiaddr).to_str() {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+        let relay_addr = match relay_addr_str.parse::<Multiaddr>() {
+            Ok(a) => a,
+            Err(e) => {
+                error!("Invalid relay multiaddr: {}", e);
+                return false;
+            }
+        };
+
+        let inner = &mut *(*node).inner;
+        match inner.add_relay(relay_addr) {
+            Ok(_) => true,
+            Err(e) => {
+                error!("Failed to add relay: {}", e);
+                false
+            }
+        }
+    }
+}
+
+// Reports cumulative bytes moved over the transport since the swarm was
+// initialized, so the Swift UI can display and cap data usage.
+#[no_mangle]
+pub extern "C" fn p2p_get_bandwidth(
+    node: *mut P2PNode,
+    out_inbound: *mut u64,
+    out_outbound: *mut u64,
+) -> bool {
+    if node.is_null() || out_inbound.is_null() || out_outbound.is_null() {
+        return false;
+    }
+
+    unsafe {
+        let inner = &*(*node).inner;
+        match inner.bandwidth_totals() {
+            Ok((inbound, outbound)) => {
+                *out_inbound = inbound;
+                *out_outbound = outbound;
+                true
+            }
+            Err(e) => {
+                error!("Failed to read bandwidth totals: {}", e);
+                false
+            }
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn p2p_get_peer_id(node: *mut P2PNode) -> *const c_char {
     if node.is_null() {
         return std::ptr::null();
     }
-    
+
     unsafe {
         let inner = &*(*node).inner;
         let peer_id_str = CString::new(inner.peer_id.to_string()).unwrap();
@@ -423,4 +1377,104 @@ pub extern "C" fn p2p_free_string(s: *mut c_char) {
 pub extern "C" fn p2p_init_logging() {
     tracing_subscriber::fmt::init();
     info!("libp2p FFI initialized");
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_export_import_round_trips() {
+        let node = NodeInner::new().expect("new node");
+        let node = P2PNode {
+            inner: Box::into_raw(Box::new(node)),
+        };
+        let node = Box::into_raw(Box::new(node));
+
+        let exported = p2p_node_export_identity(node);
+        assert!(!exported.is_null());
+        let exported_str = unsafe { CStr::from_ptr(exported) }.to_owned();
+
+        let original_peer_id = unsafe { (*(*node).inner).peer_id };
+
+        let reimported = p2p_node_create_with_identity(
+            exported_str.as_ptr() as *const u8,
+            exported_str.as_bytes().len(),
+        );
+        assert!(!reimported.is_null());
+        let reimported_peer_id = unsafe { (*(*reimported).inner).peer_id };
+
+        assert_eq!(original_peer_id, reimported_peer_id);
+
+        p2p_free_string(exported);
+        p2p_node_destroy(node);
+        p2p_node_destroy(reimported);
+    }
+
+    #[test]
+    fn identity_import_rejects_garbage() {
+        let garbage = b"not valid base64 protobuf!!";
+        let node = p2p_node_create_with_identity(garbage.as_ptr(), garbage.len());
+        assert!(node.is_null());
+    }
+
+    #[test]
+    fn room_payload_round_trips_through_encrypt_decrypt() {
+        let key = derive_room_key("room-1", "correct horse battery staple").unwrap();
+        let plaintext = b"hello from the other side";
+
+        let ciphertext = encrypt_room_payload(&key, plaintext).unwrap();
+        let decrypted = decrypt_room_payload(&key, &ciphertext).unwrap();
+
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn room_payload_rejects_truncated_ciphertext() {
+        let key = derive_room_key("room-1", "correct horse battery staple").unwrap();
+        let ciphertext = encrypt_room_payload(&key, b"hello").unwrap();
+
+        // Shorter than the 12-byte nonce prefix.
+        assert!(decrypt_room_payload(&key, &ciphertext[..5]).is_err());
+        // Long enough to contain a nonce, but the AEAD tag is missing/corrupt.
+        assert!(decrypt_room_payload(&key, &ciphertext[..ciphertext.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn room_payload_rejects_wrong_key() {
+        let key = derive_room_key("room-1", "correct horse battery staple").unwrap();
+        let wrong_key = derive_room_key("room-1", "a different passphrase").unwrap();
+        let ciphertext = encrypt_room_payload(&key, b"hello").unwrap();
+
+        assert!(decrypt_room_payload(&wrong_key, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn derive_room_key_differs_per_room_for_same_passphrase() {
+        let key_a = derive_room_key("room-a", "shared passphrase").unwrap();
+        let key_b = derive_room_key("room-b", "shared passphrase").unwrap();
+        assert_ne!(key_a, key_b);
+    }
+
+    #[test]
+    fn gossipsub_load_profile_clamps_out_of_range_values() {
+        let min = gossipsub_load_profile(0);
+        let low = gossipsub_load_profile(1);
+        assert_eq!(min.heartbeat_interval, low.heartbeat_interval);
+        assert_eq!(min.mesh_n, low.mesh_n);
+
+        let max = gossipsub_load_profile(255);
+        let high = gossipsub_load_profile(5);
+        assert_eq!(max.heartbeat_interval, high.heartbeat_interval);
+        assert_eq!(max.mesh_n, high.mesh_n);
+    }
+
+    #[test]
+    fn gossipsub_load_profile_increases_aggressiveness_with_load() {
+        let low = gossipsub_load_profile(1);
+        let high = gossipsub_load_profile(5);
+        assert!(high.heartbeat_interval < low.heartbeat_interval);
+        assert!(high.mesh_n > low.mesh_n);
+        assert!(high.gossip_factor > low.gossip_factor);
+    }
+}